@@ -0,0 +1,93 @@
+use libc::mode_t;
+use std::ffi::{CStr, CString};
+use std::io;
+
+use crate::AtDir;
+
+/// Builds directories relative to an [`AtDir`], optionally creating missing
+/// parent components (`mkdir -p`).
+#[derive(Debug, Clone)]
+pub struct DirBuilder {
+    recursive: bool,
+    mode: mode_t,
+}
+
+impl Default for DirBuilder {
+    fn default() -> DirBuilder {
+        DirBuilder {
+            recursive: false,
+            mode: 0o777,
+        }
+    }
+}
+
+impl DirBuilder {
+    pub fn new() -> DirBuilder {
+        DirBuilder::default()
+    }
+
+    /// When set, missing parent components are created as needed and an
+    /// already-existing leaf directory is not an error.
+    pub fn recursive(&mut self, recursive: bool) -> &mut Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn mode(&mut self, mode: mode_t) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Creates `path` relative to `dir`. Without [`DirBuilder::recursive`]
+    /// this is a single `mkdirat`; with it, each path component is created
+    /// in turn relative to its already-opened parent, treating `EEXIST` on
+    /// an intermediate component as success and erroring if the leaf
+    /// already exists as something other than a directory.
+    ///
+    /// Each intermediate component is opened with `open_dir`'s `O_NOFOLLOW`,
+    /// so unlike `mkdir -p`, a symlinked intermediate directory fails (with
+    /// `ENOTDIR` on Linux) instead of being followed.
+    pub fn create(&self, dir: &AtDir, path: &CStr) -> io::Result<()> {
+        if self.recursive {
+            self.create_recursive(dir, path.to_bytes())
+        } else {
+            dir.mkdir(path, self.mode)
+        }
+    }
+
+    fn create_recursive(&self, dir: &AtDir, path: &[u8]) -> io::Result<()> {
+        let mut components = path
+            .split(|&b| b == b'/')
+            .filter(|component| !component.is_empty())
+            .peekable();
+
+        let mut owned_current: Option<AtDir> = None;
+
+        while let Some(component) = components.next() {
+            let name = CString::new(component).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "path component contains a NUL byte")
+            })?;
+
+            let is_leaf = components.peek().is_none();
+            let current = owned_current.as_ref().unwrap_or(dir);
+            match current.mkdir(&name, self.mode) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    if is_leaf {
+                        let st = current.stat(&name, libc::AT_SYMLINK_NOFOLLOW)?;
+                        if st.st_mode & libc::S_IFMT != libc::S_IFDIR {
+                            return Err(err);
+                        }
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+
+            if !is_leaf {
+                owned_current = Some(current.open_dir(&name)?);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use libc::{c_int, mode_t};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::sync::Arc;
+
+use crate::AtDir;
+
+/// The type of a directory entry, as reported by `d_type` or derived from
+/// `st_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+    Unknown,
+}
+
+impl FileType {
+    fn from_d_type(d_type: u8) -> FileType {
+        match d_type {
+            libc::DT_REG => FileType::File,
+            libc::DT_DIR => FileType::Dir,
+            libc::DT_LNK => FileType::Symlink,
+            libc::DT_FIFO => FileType::Fifo,
+            libc::DT_SOCK => FileType::Socket,
+            libc::DT_BLK => FileType::BlockDevice,
+            libc::DT_CHR => FileType::CharDevice,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /// Derives a [`FileType`] from `st_mode`/`stx_mode`'s `S_IFMT` bits.
+    pub(crate) fn from_mode(mode: mode_t) -> FileType {
+        match mode & libc::S_IFMT {
+            libc::S_IFREG => FileType::File,
+            libc::S_IFDIR => FileType::Dir,
+            libc::S_IFLNK => FileType::Symlink,
+            libc::S_IFIFO => FileType::Fifo,
+            libc::S_IFSOCK => FileType::Socket,
+            libc::S_IFBLK => FileType::BlockDevice,
+            libc::S_IFCHR => FileType::CharDevice,
+            _ => FileType::Unknown,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        *self == FileType::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        *self == FileType::Dir
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        *self == FileType::Symlink
+    }
+}
+
+/// The shared, refcounted tail of a `fdopendir(3)` stream.
+struct InnerReadDir {
+    dirp: *mut libc::DIR,
+    // fd owned by `dirp`, kept around so `DirEntry::stat` can fstatat()
+    // against it; never closed directly, `closedir` closes it for us.
+    root: c_int,
+}
+
+// `libc::DIR*` is not `Send`/`Sync` by default, but we never hand out
+// concurrent access to it: all use goes through `&mut ReadDir::next`.
+unsafe impl Send for InnerReadDir {}
+unsafe impl Sync for InnerReadDir {}
+
+impl Drop for InnerReadDir {
+    fn drop(&mut self) {
+        unsafe {
+            libc::closedir(self.dirp);
+        }
+    }
+}
+
+/// An iterator over the entries of a directory, opened via
+/// [`AtDir::read_dir`]. The underlying `DIR*` is shared so `DirEntry`s can
+/// outlive a single `next()` call and still `stat` themselves.
+pub struct ReadDir {
+    inner: Arc<InnerReadDir>,
+}
+
+impl AtDir {
+    /// Opens a directory stream over this directory's entries.
+    ///
+    /// Dups `self`'s fd and hands the dup to `fdopendir(3)`, so the returned
+    /// `ReadDir` can be iterated independently of further use of `self`.
+    pub fn read_dir(&self) -> io::Result<ReadDir> {
+        unsafe {
+            let dup_fd = libc::fcntl(self.root, libc::F_DUPFD_CLOEXEC, 0);
+            if dup_fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let dirp = libc::fdopendir(dup_fd);
+            if dirp.is_null() {
+                let err = io::Error::last_os_error();
+                libc::close(dup_fd);
+                return Err(err);
+            }
+            Ok(ReadDir {
+                inner: Arc::new(InnerReadDir { dirp, root: dup_fd }),
+            })
+        }
+    }
+
+    /// Recursively walks the directory tree rooted at `self`, descending
+    /// into each subdirectory by `open_dir`ing it relative to its
+    /// already-opened parent.
+    pub fn walk(&self) -> io::Result<Walk> {
+        let root = self.open_dir(&CStr::from_bytes_with_nul(b".\0").unwrap())?;
+        let read_dir = root.read_dir()?;
+        Ok(Walk {
+            stack: vec![(root, read_dir, CString::new(Vec::new()).unwrap())],
+        })
+    }
+}
+
+fn join(prefix: &CStr, name: &CStr) -> CString {
+    if prefix.to_bytes().is_empty() {
+        name.to_owned()
+    } else {
+        let mut path = prefix.to_bytes().to_vec();
+        path.push(b'/');
+        path.extend_from_slice(name.to_bytes());
+        CString::new(path).expect("names and prefix are already NUL-free CStrs")
+    }
+}
+
+/// An entry yielded while walking a tree with [`AtDir::walk`].
+#[derive(Debug)]
+pub struct WalkEntry {
+    /// The entry's path, relative to the `AtDir` `walk` was called on.
+    pub path: CString,
+    pub entry: DirEntry,
+}
+
+/// A recursive, depth-first iterator over a directory tree, built on
+/// [`ReadDir`] and [`AtDir::open_dir`].
+pub struct Walk {
+    // one (directory, its still-open stream, its relative path prefix) per
+    // level currently being descended; `next()` pops finished levels off
+    // the back and pushes a new one when it walks into a subdirectory.
+    stack: Vec<(AtDir, ReadDir, CString)>,
+}
+
+impl Iterator for Walk {
+    type Item = io::Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<io::Result<WalkEntry>> {
+        loop {
+            let (dir, read_dir, prefix) = self.stack.last_mut()?;
+            match read_dir.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                Some(Ok(entry)) => {
+                    let path = join(prefix, entry.name());
+                    let is_dir = match entry.file_type() {
+                        FileType::Dir => true,
+                        // stat with AT_SYMLINK_NOFOLLOW: a symlink-to-dir must
+                        // never be treated as descendable, since open_dir's
+                        // O_NOFOLLOW would just fail on it (ENOTDIR on Linux).
+                        FileType::Unknown => entry
+                            .lstat()
+                            .map(|st| st.st_mode & libc::S_IFMT == libc::S_IFDIR)
+                            .unwrap_or(false),
+                        _ => false,
+                    };
+
+                    if is_dir {
+                        let child = match dir.open_dir(entry.name()) {
+                            Ok(child) => child,
+                            Err(err) => return Some(Err(err)),
+                        };
+                        let child_read_dir = match child.read_dir() {
+                            Ok(read_dir) => read_dir,
+                            Err(err) => return Some(Err(err)),
+                        };
+                        self.stack.push((child, child_read_dir, path.clone()));
+                    }
+
+                    return Some(Ok(WalkEntry { path, entry }));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        unsafe {
+            loop {
+                *libc::__errno_location() = 0;
+                let entry = libc::readdir(self.inner.dirp);
+                if entry.is_null() {
+                    return match io::Error::last_os_error().raw_os_error() {
+                        Some(0) | None => None,
+                        _ => Some(Err(io::Error::last_os_error())),
+                    };
+                }
+
+                let name = CStr::from_ptr((*entry).d_name.as_ptr());
+                if name.to_bytes() == b"." || name.to_bytes() == b".." {
+                    continue;
+                }
+
+                return Some(Ok(DirEntry {
+                    inner: self.inner.clone(),
+                    name: name.to_owned(),
+                    file_type: FileType::from_d_type((*entry).d_type),
+                }));
+            }
+        }
+    }
+}
+
+/// A single entry yielded by [`ReadDir`].
+pub struct DirEntry {
+    inner: Arc<InnerReadDir>,
+    name: std::ffi::CString,
+    file_type: FileType,
+}
+
+impl DirEntry {
+    /// The entry's file name, relative to the directory it was read from.
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
+
+    /// The entry's `d_type`, translated into a [`FileType`]. May be
+    /// [`FileType::Unknown`] on filesystems that don't report `d_type`; use
+    /// [`DirEntry::stat`] in that case.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// `fstatat`s this entry's name against the parent directory, for when
+    /// [`DirEntry::file_type`] comes back [`FileType::Unknown`].
+    pub fn stat(&self) -> io::Result<libc::stat> {
+        let mut statbuf = std::mem::MaybeUninit::uninit();
+        let success = unsafe {
+            libc::fstatat(
+                self.inner.root,
+                self.name.as_ptr(),
+                statbuf.as_mut_ptr(),
+                0,
+            )
+        };
+        if success == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(unsafe { statbuf.assume_init() })
+        }
+    }
+
+    /// Like [`DirEntry::stat`], but doesn't follow a symlink entry.
+    fn lstat(&self) -> io::Result<libc::stat> {
+        let mut statbuf = std::mem::MaybeUninit::uninit();
+        let success = unsafe {
+            libc::fstatat(
+                self.inner.root,
+                self.name.as_ptr(),
+                statbuf.as_mut_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        if success == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(unsafe { statbuf.assume_init() })
+        }
+    }
+}
+
+impl std::fmt::Debug for DirEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DirEntry").field("name", &self.name).finish()
+    }
+}
@@ -0,0 +1,150 @@
+use libc::{c_int, mode_t};
+use std::ffi::CStr;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::sys::cvt_r;
+use crate::AtDir;
+
+/// An owned file descriptor opened relative to an [`AtDir`] via
+/// [`OpenOptions::open`]. Closed on `Drop`.
+#[derive(Debug)]
+pub struct File {
+    fd: c_int,
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+impl AsRawFd for File {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+/// Builds the `O_*` flag combination for an `openat(2)` call relative to an
+/// [`AtDir`].
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    mode: mode_t,
+    custom_flags: c_int,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            mode: 0o666,
+            custom_flags: 0,
+        }
+    }
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// The permission bits used when a new file is created.
+    pub fn mode(&mut self, mode: mode_t) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Extra `O_*` flags or'd into the flags `open` computes, for anything
+    /// not covered by the named setters (e.g. `O_NOATIME`, `O_DIRECT`).
+    pub fn custom_flags(&mut self, flags: c_int) -> &mut Self {
+        self.custom_flags = flags;
+        self
+    }
+
+    fn access_mode(&self) -> io::Result<c_int> {
+        match (self.read, self.write, self.append) {
+            (true, false, false) => Ok(libc::O_RDONLY),
+            (false, true, false) => Ok(libc::O_WRONLY),
+            (true, true, false) => Ok(libc::O_RDWR),
+            (false, _, true) => Ok(libc::O_WRONLY | libc::O_APPEND),
+            (true, _, true) => Ok(libc::O_RDWR | libc::O_APPEND),
+            (false, false, false) => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    fn creation_mode(&self) -> io::Result<c_int> {
+        match (self.write, self.append) {
+            (true, false) => {}
+            (false, false) => {
+                if self.truncate || self.create || self.create_new {
+                    return Err(io::Error::from_raw_os_error(libc::EINVAL));
+                }
+            }
+            (_, true) => {
+                if self.truncate && !self.create_new {
+                    return Err(io::Error::from_raw_os_error(libc::EINVAL));
+                }
+            }
+        }
+
+        Ok(match (self.create, self.truncate, self.create_new) {
+            (false, false, false) => 0,
+            (true, false, false) => libc::O_CREAT,
+            (false, true, false) => libc::O_TRUNC,
+            (true, true, false) => libc::O_CREAT | libc::O_TRUNC,
+            (_, _, true) => libc::O_CREAT | libc::O_EXCL,
+        })
+    }
+
+    /// Opens `pathname` relative to `dir` with the flags accumulated so far.
+    pub fn open(&self, dir: &AtDir, pathname: &CStr) -> io::Result<File> {
+        let flags = libc::O_CLOEXEC | self.access_mode()? | self.creation_mode()? | self.custom_flags;
+        let fd = cvt_r(dir.no_restart(), || unsafe {
+            libc::openat(dir.root, pathname.as_ptr(), flags, self.mode as c_int)
+        })?;
+        Ok(File { fd })
+    }
+}
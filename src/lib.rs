@@ -5,9 +5,24 @@ use std::ffi::CStr;
 use std::io;
 use std::mem::MaybeUninit;
 
+mod dirbuilder;
+mod dirent;
+mod file;
+mod metadata;
+mod sys;
+
+pub use dirbuilder::DirBuilder;
+pub use dirent::{DirEntry, FileType, ReadDir, Walk, WalkEntry};
+pub use file::{File, OpenOptions};
+pub use metadata::{Metadata, Permissions};
+
+use std::os::unix::io::AsRawFd;
+use sys::cvt_r;
+
 #[derive(Debug)]
 pub struct AtDir {
-    root: c_int,
+    pub(crate) root: c_int,
+    no_restart: bool,
 }
 
 impl Drop for AtDir {
@@ -27,16 +42,21 @@ impl AtDir {
             Err(io::Error::last_os_error())
         } else {
             info!("created {}", fd);
-            Ok(AtDir { root: fd })
+            Ok(AtDir {
+                root: fd,
+                no_restart: false,
+            })
         }
     }
 
-    fn ret_fd(fd: c_int) -> io::Result<c_int> {
-        if fd < 0 {
-            Err(io::Error::last_os_error())
-        } else {
-            Ok(fd)
-        }
+    /// Whether blocking syscalls on this handle surface `EINTR` to the
+    /// caller instead of transparently retrying. Off by default.
+    pub fn no_restart(&self) -> bool {
+        self.no_restart
+    }
+
+    pub fn set_no_restart(&mut self, no_restart: bool) {
+        self.no_restart = no_restart;
     }
 
     fn ret_err(success: c_int) -> io::Result<()> {
@@ -47,45 +67,56 @@ impl AtDir {
         }
     }
 
-    pub fn open(self, pathname: &CStr, flags: c_int, mode: c_int) -> io::Result<c_int> {
-        Self::ret_fd(unsafe { libc::openat(self.root, pathname.as_ptr(), flags, mode) })
+    /// Opens a child directory relative to `self`, refusing to follow a
+    /// symlink left in its place (TOCTOU-safe descent).
+    pub fn open_dir(&self, name: &CStr) -> io::Result<AtDir> {
+        let fd = cvt_r(self.no_restart, || unsafe {
+            libc::openat(
+                self.root,
+                name.as_ptr(),
+                libc::O_DIRECTORY | libc::O_CLOEXEC | libc::O_NOFOLLOW,
+            )
+        })?;
+        Ok(AtDir {
+            root: fd,
+            no_restart: self.no_restart,
+        })
+    }
+
+    pub fn open(&self, pathname: &CStr, flags: c_int, mode: c_int) -> io::Result<c_int> {
+        cvt_r(self.no_restart, || unsafe {
+            libc::openat(self.root, pathname.as_ptr(), flags, mode)
+        })
     }
 
-    pub fn close(self, fd: c_int) -> io::Result<()> {
-        Self::ret_err(unsafe { libc::close(fd) })
+    pub fn close(&self, fd: c_int) -> io::Result<()> {
+        cvt_r(self.no_restart, || unsafe { libc::close(fd) }).map(|_| ())
     }
 
-    pub fn stat(self, pathname: &CStr, flags: c_int) -> io::Result<libc::stat> {
+    pub fn stat(&self, pathname: &CStr, flags: c_int) -> io::Result<libc::stat> {
         let mut statbuf = MaybeUninit::uninit();
-        let success =
-            unsafe { libc::fstatat(self.root, pathname.as_ptr(), statbuf.as_mut_ptr(), flags) };
-        if success == -1 {
-            Err(io::Error::last_os_error())
-        } else {
-            Ok(unsafe { statbuf.assume_init() })
-        }
+        cvt_r(self.no_restart, || unsafe {
+            libc::fstatat(self.root, pathname.as_ptr(), statbuf.as_mut_ptr(), flags)
+        })?;
+        Ok(unsafe { statbuf.assume_init() })
     }
 
-    pub fn access(self, pathname: &CStr, mode: c_int, flags: c_int) -> io::Result<bool> {
-        let success = unsafe { libc::faccessat(self.root, pathname.as_ptr(), mode, flags) };
-        if success == -1 {
-            let last_error = io::Error::last_os_error();
-            if last_error.kind() == io::ErrorKind::PermissionDenied {
-                Ok(false)
-            } else {
-                Err(last_error)
-            }
-        } else {
-            Ok(true)
+    pub fn access(&self, pathname: &CStr, mode: c_int, flags: c_int) -> io::Result<bool> {
+        match cvt_r(self.no_restart, || unsafe {
+            libc::faccessat(self.root, pathname.as_ptr(), mode, flags)
+        }) {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(false),
+            Err(err) => Err(err),
         }
     }
 
-    pub fn chmod(self, pathname: &CStr, mode: mode_t, flags: c_int) -> io::Result<()> {
+    pub fn chmod(&self, pathname: &CStr, mode: mode_t, flags: c_int) -> io::Result<()> {
         Self::ret_err(unsafe { libc::fchmodat(self.root, pathname.as_ptr(), mode, flags) })
     }
 
     pub fn chown(
-        self,
+        &self,
         pathname: &CStr,
         owner: uid_t,
         group: gid_t,
@@ -94,12 +125,29 @@ impl AtDir {
         Self::ret_err(unsafe { libc::fchownat(self.root, pathname.as_ptr(), owner, group, flags) })
     }
 
-    pub fn mkdir(self, pathname: &CStr, mode: mode_t) -> io::Result<()> {
+    pub fn mkdir(&self, pathname: &CStr, mode: mode_t) -> io::Result<()> {
         Self::ret_err(unsafe { libc::mkdirat(self.root, pathname.as_ptr(), mode) })
     }
 
+    /// Creates a regular, device, or special file via `mknodat(2)`.
+    ///
+    /// `mode`'s `S_IF*` bits select what gets created: `S_IFREG` for a
+    /// regular file, `S_IFCHR`/`S_IFBLK` for a character/block device
+    /// (using `dev`, see [`makedev`]), `S_IFIFO` for a FIFO, `S_IFSOCK` for
+    /// a socket. No type bits (`0`) is equivalent to `S_IFREG`.
+    pub fn mknod(&self, pathname: &CStr, mode: mode_t, dev: libc::dev_t) -> io::Result<()> {
+        Self::ret_err(unsafe { libc::mknodat(self.root, pathname.as_ptr(), mode, dev) })
+    }
+
+    /// Creates a FIFO via `mknodat(2)` with `S_IFIFO`. `mode` is just the
+    /// permission bits; the `dev_t` argument `mknod` would otherwise need
+    /// is unused for FIFOs.
+    pub fn mkfifo(&self, pathname: &CStr, mode: mode_t) -> io::Result<()> {
+        self.mknod(pathname, (mode & 0o7777) | libc::S_IFIFO, 0)
+    }
+
     pub fn link(
-        self,
+        &self,
         oldpath: &CStr,
         newdir: Option<&AtDir>,
         newpath: &CStr,
@@ -109,27 +157,29 @@ impl AtDir {
             Some(newdir) => newdir.root,
             None => self.root,
         };
-        Self::ret_err(unsafe {
+        cvt_r(self.no_restart, || unsafe {
             libc::linkat(self.root, oldpath.as_ptr(), newdir, newpath.as_ptr(), flags)
         })
+        .map(|_| ())
     }
 
     // attention: reverses order of arguments to be consistent with self.symlink(link, target) syntax
-    pub fn symlink(self, linkpath: &CStr, target: &CStr) -> io::Result<()> {
+    pub fn symlink(&self, linkpath: &CStr, target: &CStr) -> io::Result<()> {
         Self::ret_err(unsafe { libc::symlinkat(target.as_ptr(), self.root, linkpath.as_ptr()) })
     }
 
     pub fn readlink<'a>(
-        self,
+        &self,
         pathname: &CStr,
         buf: &'a mut (dyn RxBuffer + 'a),
     ) -> io::Result<&'a [u8]> {
         unsafe {
-            let (ptr, len) = buf.as_c_char();
-            let len = libc::readlinkat(self.root, pathname.as_ptr(), ptr, len);
+            let (ptr, cap) = buf.as_c_char();
+            let len = cvt_r(self.no_restart, || {
+                libc::readlinkat(self.root, pathname.as_ptr(), ptr, cap)
+            })?;
             match len {
-                -1 => Err(io::Error::last_os_error()),
-                size if size == len => Err(io::Error::new(
+                size if size as usize == cap => Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
                     "Buffer too small",
                 )),
@@ -138,7 +188,40 @@ impl AtDir {
         }
     }
 
-    pub fn statx(self, pathname: &CStr, flags: c_int, mask: c_uint) -> io::Result<libc::statx> {
+    /// Like [`AtDir::readlink`], but grows an owned buffer until the target
+    /// fits instead of making the caller guess a size up front.
+    ///
+    /// Seeds the initial capacity from `lstat`'s `st_size`, which is
+    /// usually exact for symlinks, then doubles and retries whenever a read
+    /// fills the buffer completely (indistinguishable from truncation).
+    pub fn readlink_owned(&self, pathname: &CStr) -> io::Result<Vec<u8>> {
+        let mut cap = self
+            .stat(pathname, libc::AT_SYMLINK_NOFOLLOW)
+            .map(|st| st.st_size as usize)
+            .ok()
+            .filter(|&size| size > 0)
+            .unwrap_or(256);
+
+        loop {
+            let mut buf = vec![0u8; cap];
+            let len = cvt_r(self.no_restart, || unsafe {
+                libc::readlinkat(
+                    self.root,
+                    pathname.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_char,
+                    buf.len(),
+                )
+            })? as usize;
+
+            if len < cap {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            cap *= 2;
+        }
+    }
+
+    pub fn statx(&self, pathname: &CStr, flags: c_int, mask: c_uint) -> io::Result<libc::statx> {
         let mut statbuf = MaybeUninit::uninit();
         let success = unsafe {
             libc::statx(
@@ -156,66 +239,124 @@ impl AtDir {
         }
     }
 
-    pub fn unlink(self, pathname: &CStr, flags: c_int) -> io::Result<()> {
+    pub fn unlink(&self, pathname: &CStr, flags: c_int) -> io::Result<()> {
         Self::ret_err(unsafe { libc::unlinkat(self.root, pathname.as_ptr(), flags) })
     }
 
-    pub fn utimens(self, path: &CStr, times: &timespec, flag: c_int) -> io::Result<()> {
+    pub fn utimens(&self, path: &CStr, times: &timespec, flag: c_int) -> io::Result<()> {
         Self::ret_err(unsafe { libc::utimensat(self.root, path.as_ptr(), times, flag) })
     }
 
     pub fn fgetxattr<'a>(
-        filedes: c_int,
+        filedes: &impl AsRawFd,
         name: &CStr,
         value: &'a mut (dyn RxBuffer + 'a),
     ) -> io::Result<&'a [u8]> {
         unsafe {
-            //TODO: resize when requested
             let (ptr, len) = value.as_c_void();
-            let len = libc::fgetxattr(filedes, name.as_ptr(), ptr, len);
+            let len = cvt_r(false, || libc::fgetxattr(filedes.as_raw_fd(), name.as_ptr(), ptr, len))?;
+            Ok(value.rx_done(len as usize))
+        }
+    }
 
-            if len == -1 {
-                Err(io::Error::last_os_error())
-            } else {
-                Ok(value.rx_done(len as usize))
+    /// Like [`AtDir::fgetxattr`], but grows an owned buffer until the value
+    /// fits instead of making the caller guess a size up front.
+    pub fn fgetxattr_owned(filedes: &impl AsRawFd, name: &CStr) -> io::Result<Vec<u8>> {
+        let mut cap = 256;
+
+        loop {
+            let mut buf = vec![0u8; cap];
+            let len = match cvt_r(false, || unsafe {
+                libc::fgetxattr(
+                    filedes.as_raw_fd(),
+                    name.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            }) {
+                Ok(len) => len as usize,
+                Err(err) if err.raw_os_error() == Some(libc::ERANGE) => {
+                    cap *= 2;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if len < cap {
+                buf.truncate(len);
+                return Ok(buf);
             }
+            cap *= 2;
         }
     }
 
     pub fn fsetxattr(
-        filedes: c_int,
+        filedes: &impl AsRawFd,
         name: &CStr,
         value: &dyn TxBuffer,
         flags: c_int,
     ) -> io::Result<()> {
         let (ptr, len) = value.as_c_void();
-        Self::ret_err(unsafe { libc::fsetxattr(filedes, name.as_ptr(), ptr, len, flags) })
+        cvt_r(false, || unsafe {
+            libc::fsetxattr(filedes.as_raw_fd(), name.as_ptr(), ptr, len, flags)
+        })
+        .map(|_| ())
     }
 
-    pub fn fremovexattr(filedes: c_int, name: &CStr) -> io::Result<()> {
-        Self::ret_err(unsafe { libc::fremovexattr(filedes, name.as_ptr()) })
+    pub fn fremovexattr(filedes: &impl AsRawFd, name: &CStr) -> io::Result<()> {
+        cvt_r(false, || unsafe { libc::fremovexattr(filedes.as_raw_fd(), name.as_ptr()) }).map(|_| ())
     }
 
     pub fn flistxattr<'a>(
-        filedes: c_int,
+        filedes: &impl AsRawFd,
         list: &'a mut (dyn RxBuffer + 'a),
     ) -> io::Result<&'a [u8]> {
         unsafe {
-            //TODO: resize when requested
-            //TODO: iterators
             let (ptr, len) = list.as_c_char();
-            let len = libc::flistxattr(filedes, ptr, len);
+            let len = cvt_r(false, || libc::flistxattr(filedes.as_raw_fd(), ptr, len))?;
+            Ok(list.rx_done(len as usize))
+        }
+    }
+
+    /// Like [`AtDir::flistxattr`], but grows an owned buffer until the
+    /// whole name list fits instead of making the caller guess a size up
+    /// front. A list that grows between attempts just triggers another,
+    /// larger retry.
+    pub fn flistxattr_owned(filedes: &impl AsRawFd) -> io::Result<Vec<u8>> {
+        let mut cap = 256;
+
+        loop {
+            let mut buf = vec![0u8; cap];
+            let len = match cvt_r(false, || unsafe {
+                libc::flistxattr(
+                    filedes.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_char,
+                    buf.len(),
+                )
+            }) {
+                Ok(len) => len as usize,
+                Err(err) if err.raw_os_error() == Some(libc::ERANGE) => {
+                    cap *= 2;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
 
-            if len == -1 {
-                Err(io::Error::last_os_error())
-            } else {
-                Ok(list.rx_done(len as usize))
+            if len < cap {
+                buf.truncate(len);
+                return Ok(buf);
             }
+            cap *= 2;
         }
     }
 
-    pub fn rename(self, oldname: &CStr, newroot: Option<AtDir>, newname: &CStr) -> io::Result<()> {
-        Self::ret_err(unsafe {
+    pub fn rename(
+        &self,
+        oldname: &CStr,
+        newroot: Option<&AtDir>,
+        newname: &CStr,
+    ) -> io::Result<()> {
+        cvt_r(self.no_restart, || unsafe {
             libc::renameat(
                 self.root,
                 oldname.as_ptr(),
@@ -223,10 +364,45 @@ impl AtDir {
                 newname.as_ptr(),
             )
         })
+        .map(|_| ())
     }
+}
+
+/// Packs a major/minor device number pair into the `dev_t` expected by
+/// [`AtDir::mknod`], using glibc's `makedev(3)` bit layout.
+pub fn makedev(major: u32, minor: u32) -> libc::dev_t {
+    let major = major as u64;
+    let minor = minor as u64;
+    (((major & 0xfff) << 8)
+        | (minor & 0xff)
+        | ((major & !0xfff) << 32)
+        | ((minor & !0xff) << 12)) as libc::dev_t
+}
 
-    //PLANNED:
-    //+ mknodat(2)
-    //+ mkfifoat(3)
-    //+ scandirat(3)
+/// Iterates the NUL-separated attribute names packed into a buffer returned
+/// by [`AtDir::flistxattr`] or [`AtDir::flistxattr_owned`].
+pub struct Xattrs<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Xattrs<'a> {
+    /// Wraps a raw `flistxattr(2)` result for iteration. An empty `list`
+    /// yields no names.
+    pub fn new(list: &'a [u8]) -> Xattrs<'a> {
+        Xattrs { rest: list }
+    }
 }
+
+impl<'a> Iterator for Xattrs<'a> {
+    type Item = &'a CStr;
+
+    fn next(&mut self) -> Option<&'a CStr> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let name = CStr::from_bytes_until_nul(self.rest).ok()?;
+        self.rest = &self.rest[name.to_bytes_with_nul().len()..];
+        Some(name)
+    }
+}
+
@@ -0,0 +1,149 @@
+use libc::{c_int, c_uint, gid_t, mode_t, timespec, uid_t};
+use std::ffi::CStr;
+use std::io;
+
+use crate::dirent::FileType;
+use crate::AtDir;
+
+/// The permission bits of a file, as returned in `Metadata::permissions`.
+#[derive(Debug, Clone, Copy)]
+pub struct Permissions {
+    mode: mode_t,
+}
+
+impl Permissions {
+    /// The raw `st_mode` permission bits (the low 12 bits: type is stripped).
+    pub fn mode(&self) -> mode_t {
+        self.mode & 0o7777
+    }
+
+    /// Whether none of the owner/group/other write bits are set.
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+}
+
+/// A typed view over a `stat`/`statx` result, built by [`AtDir::metadata`]
+/// or [`AtDir::metadata_x`].
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    mode: mode_t,
+    size: u64,
+    uid: uid_t,
+    gid: gid_t,
+    accessed: timespec,
+    modified: timespec,
+    changed: timespec,
+    created: Option<timespec>,
+}
+
+impl Metadata {
+    fn from_stat(st: libc::stat) -> Metadata {
+        Metadata {
+            mode: st.st_mode,
+            size: st.st_size as u64,
+            uid: st.st_uid,
+            gid: st.st_gid,
+            accessed: timespec {
+                tv_sec: st.st_atime,
+                tv_nsec: st.st_atime_nsec,
+            },
+            modified: timespec {
+                tv_sec: st.st_mtime,
+                tv_nsec: st.st_mtime_nsec,
+            },
+            changed: timespec {
+                tv_sec: st.st_ctime,
+                tv_nsec: st.st_ctime_nsec,
+            },
+            created: None,
+        }
+    }
+
+    fn from_statx(stx: libc::statx) -> Metadata {
+        let to_timespec = |ts: libc::statx_timestamp| timespec {
+            tv_sec: ts.tv_sec,
+            tv_nsec: ts.tv_nsec as i64,
+        };
+        Metadata {
+            mode: stx.stx_mode as mode_t,
+            size: stx.stx_size,
+            uid: stx.stx_uid,
+            gid: stx.stx_gid,
+            accessed: to_timespec(stx.stx_atime),
+            modified: to_timespec(stx.stx_mtime),
+            changed: to_timespec(stx.stx_ctime),
+            created: if stx.stx_mask & libc::STATX_BTIME != 0 {
+                Some(to_timespec(stx.stx_btime))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The file size in bytes.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.mode)
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions { mode: self.mode }
+    }
+
+    pub fn uid(&self) -> uid_t {
+        self.uid
+    }
+
+    pub fn gid(&self) -> gid_t {
+        self.gid
+    }
+
+    /// Last access time.
+    pub fn accessed(&self) -> timespec {
+        self.accessed
+    }
+
+    /// Last content modification time.
+    pub fn modified(&self) -> timespec {
+        self.modified
+    }
+
+    /// Last inode/metadata change time (`st_ctime`).
+    pub fn changed(&self) -> timespec {
+        self.changed
+    }
+
+    /// Creation ("birth") time, only available when built from
+    /// [`AtDir::metadata_x`] with `STATX_BTIME` in the mask and the
+    /// filesystem actually reporting it.
+    pub fn created(&self) -> Option<timespec> {
+        self.created
+    }
+}
+
+impl AtDir {
+    /// Typed `fstatat(2)`-based metadata for `pathname`.
+    pub fn metadata(&self, pathname: &CStr, flags: c_int) -> io::Result<Metadata> {
+        self.stat(pathname, flags).map(Metadata::from_stat)
+    }
+
+    /// Typed `statx(2)`-based metadata for `pathname`, for callers who need
+    /// fields plain `stat` can't give (e.g. pass `STATX_BTIME` in `mask` for
+    /// [`Metadata::created`]).
+    pub fn metadata_x(
+        &self,
+        pathname: &CStr,
+        flags: c_int,
+        mask: c_uint,
+    ) -> io::Result<Metadata> {
+        self.statx(pathname, flags, mask).map(Metadata::from_statx)
+    }
+}
@@ -0,0 +1,40 @@
+use std::io;
+
+/// Types returned by the libc syscalls we retry: plain `-1` signals error,
+/// regardless of whether the call itself returns `c_int` or `ssize_t`.
+pub(crate) trait IsMinusOne {
+    fn is_minus_one(&self) -> bool;
+}
+
+macro_rules! impl_is_minus_one {
+    ($($t:ident)*) => ($(impl IsMinusOne for $t {
+        fn is_minus_one(&self) -> bool {
+            *self == -1
+        }
+    })*)
+}
+
+impl_is_minus_one! { i32 i64 isize }
+
+/// Retries `f` while it returns `-1` with `errno == EINTR`, mirroring the
+/// `cvt_r`/`keep_going` pattern the unix std uses around blocking syscalls.
+///
+/// Pass `no_restart = true` to let `EINTR` surface as a plain error instead,
+/// for callers who want to observe signal interruption themselves.
+pub(crate) fn cvt_r<T, F>(no_restart: bool, mut f: F) -> io::Result<T>
+where
+    T: IsMinusOne,
+    F: FnMut() -> T,
+{
+    loop {
+        let ret = f();
+        if ret.is_minus_one() {
+            let err = io::Error::last_os_error();
+            if !no_restart && err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        return Ok(ret);
+    }
+}